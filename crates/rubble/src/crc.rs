@@ -0,0 +1,113 @@
+//! BLE CRC-24 computation.
+//!
+//! Every Link-Layer PDU is followed by a 3-byte CRC. Hardware BLE radios usually generate and
+//! check this on their own, but a raw [`Radio`][crate::phy::Radio] has no special BLE support and
+//! needs the CRC computed and appended in software before `transmit`, and checked after receive.
+
+/// The generator polynomial `x^24 + x^10 + x^9 + x^6 + x^4 + x^3 + x + 1`, with the implicit
+/// `x^24` term dropped, bit-reversed for the right-shifting register: bit 23 holds the `x^0`
+/// term and bit 0 the `x^23` term.
+const POLY: u32 = 0xDA_6000;
+
+/// The CRC initialization value used for all advertising-channel PDUs.
+pub const ADVERTISING_CRC_INIT: u32 = 0x555555;
+
+/// An in-progress BLE CRC-24 computation.
+///
+/// Seed it with [`Crc24::with_init`] (`ADVERTISING_CRC_INIT` for advertising-channel PDUs, or the
+/// connection's `CRCInit` for data-channel PDUs), [`update`][Crc24::update] it with the PDU bytes,
+/// then call [`finish`][Crc24::finish] to get the 3 CRC bytes to append, in over-the-air (LSb
+/// first) order.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc24 {
+    state: u32,
+}
+
+impl Crc24 {
+    /// Creates a `Crc24` seeded with `init` (the lower 24 bits are used).
+    ///
+    /// The register is stored bit-reversed (LFSR position 0, the LSb of `init`, is bit 23).
+    pub fn with_init(init: u32) -> Self {
+        Self {
+            state: (init & 0x00FF_FFFF).reverse_bits() >> 8,
+        }
+    }
+
+    /// Feeds `data` into the running CRC, processing each byte LSb first.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.update_byte(byte);
+        }
+    }
+
+    fn update_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            let bit = (byte >> i) & 1;
+            let feedback = bit ^ (self.state & 1) as u8;
+            self.state >>= 1;
+            if feedback == 1 {
+                self.state ^= POLY;
+            }
+        }
+    }
+
+    /// Finishes the computation, returning the 3-byte CRC to transmit LSb first (i.e. `[0]` is
+    /// sent first).
+    pub fn finish(self) -> [u8; 3] {
+        [
+            self.state as u8,
+            (self.state >> 8) as u8,
+            (self.state >> 16) as u8,
+        ]
+    }
+}
+
+/// Computes the CRC-24 of `pdu_and_crc`'s PDU portion (all but the last 3 bytes) and checks it
+/// against the trailing CRC bytes.
+///
+/// Returns `true` if the CRC matches, i.e. the PDU was (most likely) received without error.
+/// Returns `false` for `pdu_and_crc` shorter than 3 bytes, which can't carry a valid CRC (a
+/// perfectly plausible way for a received packet to be garbled).
+pub fn verify(init: u32, pdu_and_crc: &[u8]) -> bool {
+    if pdu_and_crc.len() < 3 {
+        return false;
+    }
+    let split = pdu_and_crc.len() - 3;
+    let (pdu, crc) = pdu_and_crc.split_at(split);
+
+    let mut computed = Crc24::with_init(init);
+    computed.update(pdu);
+    computed.finish() == crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_advertising_crc() {
+        let mut crc = Crc24::with_init(ADVERTISING_CRC_INIT);
+        crc.update(&[0xAA, 0x01, 0x02, 0x03]);
+        assert_eq!(crc.finish(), [0x8D, 0xEC, 0xDA]);
+    }
+
+    #[test]
+    fn verifies_matching_crc() {
+        let pdu = [0xAA, 0x01, 0x02, 0x03];
+        let mut crc = Crc24::with_init(ADVERTISING_CRC_INIT);
+        crc.update(&pdu);
+
+        let mut buf = pdu.to_vec();
+        buf.extend_from_slice(&crc.finish());
+        assert!(verify(ADVERTISING_CRC_INIT, &buf));
+
+        buf[0] ^= 0x01;
+        assert!(!verify(ADVERTISING_CRC_INIT, &buf));
+    }
+
+    #[test]
+    fn verify_rejects_too_short_input_instead_of_panicking() {
+        assert!(!verify(ADVERTISING_CRC_INIT, &[]));
+        assert!(!verify(ADVERTISING_CRC_INIT, &[0x00, 0x01]));
+    }
+}
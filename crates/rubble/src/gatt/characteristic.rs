@@ -0,0 +1,457 @@
+//! Declarative service/characteristic builder.
+//!
+//! Services used to be built from hand-assembled raw byte arrays: characteristic declarations with
+//! manually packed handles, properties and UUIDs, plus identical `AttributeProvider::
+//! for_attrs_in_range` / `is_grouping_attr` / `group_end` boilerplate copy-pasted between them.
+//! [`ServiceBuilder`] is the alternative: declare the UUID, properties, value and descriptors of
+//! each characteristic, and it generates the attribute table (sequential [`Handle`]s, 0x2803
+//! declaration bytes, grouping metadata and CCCDs) for you. The resulting [`Service`] implements
+//! [`AttributeProvider`] once, generically; [`crate::gatt::BatteryServiceAttrs`] and
+//! [`crate::gatt::MidiServiceAttrs`] wrap a `Service` and reuse its `for_attrs_in_range` /
+//! `is_grouping_attr` / `group_end` bodies (exposed here as free functions) rather than
+//! implementing them again.
+
+use super::CccdState;
+use crate::att::{AttUuid, Attribute, AttributeProvider, Handle, HandleRange};
+use crate::uuid::Uuid16;
+use crate::Error;
+use core::cmp;
+use heapless::Vec;
+
+/// Characteristic properties, as stored in a Characteristic Declaration (UUID 0x2803).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Properties(u8);
+
+impl Properties {
+    /// The characteristic value may be read.
+    pub const READ: Self = Self(0x02);
+    /// The characteristic value may be written without waiting for a response.
+    pub const WRITE_WITHOUT_RESPONSE: Self = Self(0x04);
+    /// The characteristic value may be written, with a response.
+    pub const WRITE: Self = Self(0x08);
+    /// The server may send Handle Value Notifications for this characteristic.
+    pub const NOTIFY: Self = Self(0x10);
+    /// The server may send Handle Value Indications for this characteristic.
+    pub const INDICATE: Self = Self(0x20);
+
+    /// Returns the raw properties byte to store in the Characteristic Declaration.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Combines `self` with `other`, keeping every property set in either.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns whether every property in `other` is also set in `self`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Properties {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+/// The maximum size, in bytes, of a generated Characteristic Declaration value (properties byte +
+/// 2-byte handle + a 128-bit UUID).
+const MAX_DECL_LEN: usize = 1 + 2 + 16;
+
+/// Owned or borrowed attribute value storage, so [`Service`] can mix user-supplied `'static`
+/// characteristic values with the declaration/CCCD bytes it generates at build time.
+pub enum AttrValue {
+    /// A `'static` byte slice, e.g. a characteristic value supplied by the caller.
+    Static(&'static [u8]),
+    /// Bytes generated while building the attribute table (a declaration record or a CCCD).
+    Owned(Vec<u8, MAX_DECL_LEN>),
+}
+
+impl AsRef<[u8]> for AttrValue {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            AttrValue::Static(bytes) => bytes,
+            AttrValue::Owned(bytes) => bytes.as_slice(),
+        }
+    }
+}
+
+/// Declares one characteristic to add to a [`ServiceBuilder`].
+pub struct CharacteristicSpec {
+    uuid: AttUuid,
+    properties: Properties,
+    value: &'static [u8],
+    cccd: bool,
+}
+
+impl CharacteristicSpec {
+    /// Declares a characteristic with the given `uuid`, `properties` and initial `value`.
+    pub fn new(uuid: AttUuid, properties: Properties, value: &'static [u8]) -> Self {
+        Self {
+            uuid,
+            properties,
+            value,
+            cccd: false,
+        }
+    }
+
+    /// Adds a CCCD (UUID 0x2902) descriptor, letting clients subscribe to notifications or
+    /// indications.
+    ///
+    /// This only makes sense alongside [`Properties::NOTIFY`] and/or [`Properties::INDICATE`].
+    pub fn with_cccd(mut self) -> Self {
+        self.cccd = true;
+        self
+    }
+}
+
+/// Builds the attribute table for a GATT service from a declarative list of characteristics.
+///
+/// `N` is the total number of attributes the service will have: 1 (service declaration) plus, for
+/// each characteristic, 2 (declaration + value) or 3 if [`CharacteristicSpec::with_cccd`] was
+/// used.
+pub struct ServiceBuilder<const N: usize> {
+    service_uuid: AttUuid,
+    attributes: Vec<Attribute<AttrValue>, N>,
+    cccds: Vec<CccdEntry, N>,
+}
+
+impl<const N: usize> ServiceBuilder<N> {
+    /// Starts building a service with the given `uuid` and raw `uuid_bytes` (the wire
+    /// representation stored in the Primary Service Declaration, UUID 0x2800).
+    pub fn new(uuid: AttUuid, uuid_bytes: &'static [u8]) -> Self {
+        let mut attributes = Vec::new();
+        attributes
+            .push(Attribute::new(
+                Uuid16(0x2800).into(),
+                Handle::from_raw(1),
+                AttrValue::Static(uuid_bytes),
+            ))
+            .ok()
+            .expect("ServiceBuilder capacity too small for the service declaration");
+
+        Self {
+            service_uuid: uuid,
+            attributes,
+            cccds: Vec::new(),
+        }
+    }
+
+    /// Adds a characteristic, appending its declaration, value and (if requested) CCCD attributes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is too small to hold the attributes generated so far plus this
+    /// characteristic's.
+    pub fn characteristic(mut self, spec: CharacteristicSpec) -> Self {
+        let decl_handle = Handle::from_raw(self.attributes.len() as u16 + 1);
+        let value_handle = Handle::from_raw(decl_handle.as_u16() + 1);
+
+        let mut decl = Vec::<u8, MAX_DECL_LEN>::new();
+        decl.push(spec.properties.bits()).ok().unwrap();
+        decl.extend_from_slice(&value_handle.as_u16().to_le_bytes())
+            .ok()
+            .unwrap();
+        append_uuid_bytes(&spec.uuid, &mut decl);
+
+        self.attributes
+            .push(Attribute::new(
+                Uuid16(0x2803).into(),
+                decl_handle,
+                AttrValue::Owned(decl),
+            ))
+            .ok()
+            .expect("ServiceBuilder capacity too small for a characteristic declaration");
+
+        self.attributes
+            .push(Attribute::new(
+                spec.uuid,
+                value_handle,
+                AttrValue::Static(spec.value),
+            ))
+            .ok()
+            .expect("ServiceBuilder capacity too small for a characteristic value");
+
+        if spec.cccd {
+            let cccd_handle = Handle::from_raw(value_handle.as_u16() + 1);
+            let mut cccd_value = Vec::<u8, MAX_DECL_LEN>::new();
+            cccd_value.extend_from_slice(&[0x00, 0x00]).ok().unwrap();
+
+            self.attributes
+                .push(Attribute::new(
+                    AttUuid::Uuid16(Uuid16(0x2902)),
+                    cccd_handle,
+                    AttrValue::Owned(cccd_value),
+                ))
+                .ok()
+                .expect("ServiceBuilder capacity too small for a CCCD");
+
+            self.cccds
+                .push(CccdEntry {
+                    cccd_handle,
+                    value_handle,
+                    state: CccdState::default(),
+                    indication_pending: false,
+                })
+                .ok()
+                .expect("ServiceBuilder capacity too small for a CCCD");
+        }
+
+        self
+    }
+
+    /// Finishes building the service.
+    pub fn build(self) -> Service<N> {
+        Service {
+            service_uuid: self.service_uuid,
+            attributes: self.attributes,
+            cccds: self.cccds,
+        }
+    }
+}
+
+/// Writes the wire bytes of `uuid` (2 bytes for a 16-bit UUID, 16 bytes for a 128-bit UUID) to
+/// `out`.
+fn append_uuid_bytes(uuid: &AttUuid, out: &mut Vec<u8, MAX_DECL_LEN>) {
+    match uuid {
+        AttUuid::Uuid16(uuid) => {
+            out.extend_from_slice(&uuid.0.to_le_bytes()).ok().unwrap();
+        }
+        AttUuid::Uuid128(uuid) => {
+            out.extend_from_slice(&uuid.to_bytes()).ok().unwrap();
+        }
+    }
+}
+
+/// Tracks the CCCD state for one notifiable/indicatable characteristic of a [`Service`].
+struct CccdEntry {
+    cccd_handle: Handle,
+    value_handle: Handle,
+    state: CccdState,
+    /// Whether an indication has been sent for this characteristic and not yet confirmed; see
+    /// [`crate::gatt::NotifySource::indication_pending`].
+    indication_pending: bool,
+}
+
+/// A GATT service built by [`ServiceBuilder`].
+///
+/// Implements [`AttributeProvider`] generically from its attribute table, so services built this
+/// way never need to hand-write `for_attrs_in_range`, `is_grouping_attr` or `group_end`.
+pub struct Service<const N: usize> {
+    service_uuid: AttUuid,
+    attributes: Vec<Attribute<AttrValue>, N>,
+    cccds: Vec<CccdEntry, N>,
+}
+
+impl<const N: usize> Service<N> {
+    /// Returns this service's UUID.
+    pub fn uuid(&self) -> &AttUuid {
+        &self.service_uuid
+    }
+
+    /// Returns the attribute table, in handle order starting at handle 1.
+    pub(crate) fn attributes(&self) -> &[Attribute<AttrValue>] {
+        &self.attributes
+    }
+
+    /// Returns the current CCCD state for the characteristic whose value attribute is at
+    /// `value_handle`.
+    ///
+    /// Returns `None` if `value_handle` doesn't name a notifiable/indicatable characteristic of
+    /// this service.
+    pub fn cccd_state(&self, value_handle: Handle) -> Option<CccdState> {
+        self.cccds
+            .iter()
+            .find(|entry| entry.value_handle.as_u16() == value_handle.as_u16())
+            .map(|entry| entry.state)
+    }
+
+    /// Returns whether an indication has been sent for the characteristic whose value attribute is
+    /// at `value_handle`, and not yet confirmed.
+    ///
+    /// Returns `false` if `value_handle` doesn't name a notifiable/indicatable characteristic of
+    /// this service.
+    pub fn indication_pending(&self, value_handle: Handle) -> bool {
+        self.cccds
+            .iter()
+            .find(|entry| entry.value_handle.as_u16() == value_handle.as_u16())
+            .is_some_and(|entry| entry.indication_pending)
+    }
+
+    /// Directly sets the in-flight indication flag for the characteristic whose value attribute is
+    /// at `value_handle`.
+    ///
+    /// Does nothing if `value_handle` doesn't name a notifiable/indicatable characteristic of this
+    /// service. This, along with [`Service::cccd_state`] and [`Service::indication_pending`], is the
+    /// state [`crate::gatt::NotifySource`]'s default `notify`/`confirm` methods are built on — a
+    /// `Service`-backed provider implements `NotifySource` on top of these rather than
+    /// reimplementing the indicate/confirm protocol itself.
+    pub fn set_indication_pending(&mut self, value_handle: Handle, pending: bool) {
+        if let Some(entry) = self
+            .cccds
+            .iter_mut()
+            .find(|entry| entry.value_handle.as_u16() == value_handle.as_u16())
+        {
+            entry.indication_pending = pending;
+        }
+    }
+
+    /// Updates the CCCD state after a peer writes `data` to the CCCD at `handle`.
+    ///
+    /// Does nothing if `handle` isn't one of this service's CCCDs.
+    pub fn on_write(&mut self, handle: Handle, data: &[u8]) {
+        if data.len() < 2 {
+            return;
+        }
+        let bits = u16::from_le_bytes([data[0], data[1]]);
+        if let Some(entry) = self
+            .cccds
+            .iter_mut()
+            .find(|entry| entry.cccd_handle.as_u16() == handle.as_u16())
+        {
+            entry.state = CccdState::from_raw(bits);
+        }
+    }
+}
+
+impl<const N: usize> AttributeProvider for Service<N> {
+    fn for_attrs_in_range(
+        &mut self,
+        range: HandleRange,
+        f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let this: &Self = self;
+        for_attrs_in_range(this, &this.attributes, range, f)
+    }
+
+    fn is_grouping_attr(&self, uuid: AttUuid) -> bool {
+        is_grouping_attr(uuid)
+    }
+
+    fn group_end(&self, handle: Handle) -> Option<&Attribute<dyn AsRef<[u8]>>> {
+        group_end(&self.attributes, handle)
+    }
+}
+
+/// Shared `AttributeProvider::for_attrs_in_range` body for any provider whose attributes are a
+/// flat table in handle order, starting at handle 1 — used by [`Service`] and by hand-written
+/// providers like [`crate::gatt::BatteryServiceAttrs`] that wrap one.
+pub(crate) fn for_attrs_in_range<'p, P, T: AsRef<[u8]>>(
+    provider: &'p P,
+    attributes: &'p [Attribute<T>],
+    range: HandleRange,
+    mut f: impl FnMut(&'p P, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let count = attributes.len();
+    let start = usize::from(range.start().as_u16() - 1); // handles start at 1, not 0
+    let end = usize::from(range.end().as_u16() - 1);
+
+    let attrs = if start >= count {
+        &[]
+    } else {
+        let end = cmp::min(count - 1, end);
+        &attributes[start..=end]
+    };
+
+    for attr in attrs {
+        f(provider, attr)?;
+    }
+    Ok(())
+}
+
+/// Shared `AttributeProvider::is_grouping_attr` body: only Primary Service declarations (UUID
+/// 0x2800) group the attributes that follow them.
+pub(crate) fn is_grouping_attr(uuid: AttUuid) -> bool {
+    uuid == Uuid16(0x2800) // FIXME not characteristics?
+}
+
+/// Shared `AttributeProvider::group_end` body for a flat attribute table whose first attribute
+/// (handle 1) is the service declaration, and whose last attribute ends the group.
+pub(crate) fn group_end<T: AsRef<[u8]>>(
+    attributes: &[Attribute<T>],
+    handle: Handle,
+) -> Option<&Attribute<dyn AsRef<[u8]>>> {
+    if handle.as_u16() == 1 {
+        attributes.last()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A service with one READ | NOTIFY characteristic and a CCCD: 1 (service decl) + 2
+    /// (characteristic decl, value) + 1 (CCCD) = 4 attributes.
+    fn sample_service() -> Service<4> {
+        ServiceBuilder::<4>::new(Uuid16(0x180F).into(), &[0x0F, 0x18])
+            .characteristic(
+                CharacteristicSpec::new(
+                    Uuid16(0x2A19).into(),
+                    Properties::READ | Properties::NOTIFY,
+                    &[48],
+                )
+                .with_cccd(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn assigns_sequential_handles_starting_at_one() {
+        let service = sample_service();
+        let handles: [u16; 4] = core::array::from_fn(|i| service.attributes()[i].handle.as_u16());
+        assert_eq!(handles, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn packs_characteristic_declaration_bytes() {
+        let service = sample_service();
+        let decl = &service.attributes()[1];
+        assert_eq!(decl.att_type, Uuid16(0x2803));
+        assert_eq!(
+            decl.value.as_ref(),
+            &[
+                (Properties::READ | Properties::NOTIFY).bits(),
+                0x03,
+                0x00, // value handle = 3, little-endian
+                0x19,
+                0x2A, // UUID = 0x2A19, little-endian
+            ]
+        );
+    }
+
+    #[test]
+    fn cccd_defaults_to_disabled_and_updates_on_write() {
+        let mut service = sample_service();
+        let value_handle = Handle::from_raw(3);
+        let cccd_handle = Handle::from_raw(4);
+
+        assert_eq!(service.cccd_state(value_handle), Some(CccdState::default()));
+
+        service.on_write(cccd_handle, &[0x01, 0x00]); // enable notifications
+        assert!(service
+            .cccd_state(value_handle)
+            .unwrap()
+            .notifications_enabled());
+    }
+
+    /// The indicate/confirm protocol decision itself (preferring an indication, withholding a
+    /// second one until confirmed) lives once in `NotifySource::notify`/`confirm` and is tested
+    /// there; `Service` only needs to store and report the flag correctly.
+    #[test]
+    fn tracks_indication_pending_state() {
+        let mut service = sample_service();
+        let value_handle = Handle::from_raw(3);
+
+        assert!(!service.indication_pending(value_handle));
+        service.set_indication_pending(value_handle, true);
+        assert!(service.indication_pending(value_handle));
+        service.set_indication_pending(value_handle, false);
+        assert!(!service.indication_pending(value_handle));
+    }
+}
@@ -6,41 +6,41 @@
 pub mod characteristic;
 
 use crate::att::{AttUuid, Attribute, AttributeProvider, Handle, HandleRange};
+use crate::gatt::characteristic::{CharacteristicSpec, Properties, Service, ServiceBuilder};
 use crate::uuid::{Uuid128, Uuid16};
 use crate::Error;
-use core::cmp;
 
 /// A demo `AttributeProvider` that will enumerate as a *Battery Service*.
 pub struct BatteryServiceAttrs {
-    attributes: [Attribute<&'static [u8]>; 3],
+    service: Service<4>,
+}
+
+impl BatteryServiceAttrs {
+    /// The handle of the Battery Level characteristic's value attribute.
+    fn value_handle() -> Handle {
+        Handle::from_raw(0x0003)
+    }
+
+    /// The handle of the Battery Level characteristic's CCCD.
+    fn cccd_handle() -> Handle {
+        Handle::from_raw(0x0004)
+    }
 }
 
 impl Default for BatteryServiceAttrs {
     fn default() -> Self {
-        Self {
-            attributes: [
-                Attribute::new(
-                    Uuid16(0x2800).into(), // "Primary Service"
-                    Handle::from_raw(0x0001),
-                    &[0x0F, 0x18], // "Battery Service" = 0x180F
-                ),
-                Attribute::new(
-                    Uuid16(0x2803).into(), // "Characteristic"
-                    Handle::from_raw(0x0002),
-                    &[
-                        0x02, // 1 byte properties: READ = 0x02
-                        0x03, 0x00, // 2 bytes handle = 0x0003
-                        0x19, 0x2A, // 2 bytes UUID = 0x2A19 (Battery Level)
-                    ],
-                ),
-                // Characteristic value (Battery Level)
-                Attribute::new(
-                    AttUuid::Uuid16(Uuid16(0x2A19)), // "Battery Level"
-                    Handle::from_raw(0x0003),
+        let service = ServiceBuilder::<4>::new(Uuid16(0x180F).into(), &[0x0F, 0x18]) // "Battery Service" = 0x180F
+            .characteristic(
+                CharacteristicSpec::new(
+                    Uuid16(0x2A19).into(), // "Battery Level"
+                    Properties::READ | Properties::NOTIFY,
                     &[48u8],
-                ),
-            ],
-        }
+                )
+                .with_cccd(),
+            )
+            .build();
+
+        Self { service }
     }
 }
 
@@ -48,35 +48,44 @@ impl AttributeProvider for BatteryServiceAttrs {
     fn for_attrs_in_range(
         &mut self,
         range: HandleRange,
-        mut f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
+        f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
     ) -> Result<(), Error> {
-        let count = self.attributes.len();
-        let start = usize::from(range.start().as_u16() - 1); // handles start at 1, not 0
-        let end = usize::from(range.end().as_u16() - 1);
-
-        let attrs = if start >= count {
-            &[]
-        } else {
-            let end = cmp::min(count - 1, end);
-            &self.attributes[start..=end]
-        };
-
-        for attr in attrs {
-            f(self, attr)?;
-        }
-        Ok(())
+        let this: &Self = self;
+        characteristic::for_attrs_in_range(this, this.service.attributes(), range, f)
     }
 
     fn is_grouping_attr(&self, uuid: AttUuid) -> bool {
-        uuid == Uuid16(0x2800) // FIXME not characteristics?
+        characteristic::is_grouping_attr(uuid)
     }
 
     fn group_end(&self, handle: Handle) -> Option<&Attribute<dyn AsRef<[u8]>>> {
-        match handle.as_u16() {
-            0x0001 => Some(&self.attributes[2]),
-            0x0002 => Some(&self.attributes[2]),
-            _ => None,
-        }
+        characteristic::group_end(self.service.attributes(), handle)
+    }
+}
+
+impl NotifySource for BatteryServiceAttrs {
+    fn cccd(&self) -> CccdState {
+        self.service
+            .cccd_state(Self::value_handle())
+            .unwrap_or_default()
+    }
+
+    fn on_cccd_write(&mut self, value: u16) {
+        self.service
+            .on_write(Self::cccd_handle(), &value.to_le_bytes());
+    }
+
+    fn value_handle(&self) -> Handle {
+        Self::value_handle()
+    }
+
+    fn indication_pending(&self) -> bool {
+        self.service.indication_pending(Self::value_handle())
+    }
+
+    fn set_indication_pending(&mut self, pending: bool) {
+        self.service
+            .set_indication_pending(Self::value_handle(), pending);
     }
 }
 
@@ -84,73 +93,59 @@ impl AttributeProvider for BatteryServiceAttrs {
 ///
 /// Also refer to <https://www.midi.org/specifications-old/item/bluetooth-le-midi>.
 pub struct MidiServiceAttrs {
-    attributes: [Attribute<&'static [u8]>; 4],
+    service: Service<4>,
 }
 
 // MIDI Service (UUID: 03B80E5A-EDE8-4B33-A751-6CE34EC4C700)
 // MIDI Data I/O Characteristic (UUID: 7772E5DB-3868-4112-A1A9-F2669D106BF3)
 
+impl MidiServiceAttrs {
+    /// The handle of the MIDI Data I/O characteristic's value attribute.
+    fn value_handle() -> Handle {
+        Handle::from_raw(0x0003)
+    }
+
+    /// The handle of the MIDI Data I/O characteristic's CCCD.
+    fn cccd_handle() -> Handle {
+        Handle::from_raw(0x0004)
+    }
+}
+
 impl Default for MidiServiceAttrs {
     fn default() -> Self {
-        Self {
-            attributes: [
-                Attribute::new(
-                    Uuid16(0x2800).into(), // "Primary Service"
-                    Handle::from_raw(0x0001),
-                    &[
-                        0x00, 0xC7, 0xC4, 0x4E, 0xE3, 0x6C, /* - */
-                        0x51, 0xA7, /* - */
-                        0x33, 0x4B, /* - */
-                        0xE8, 0xED, /* - */
-                        0x5A, 0x0E, 0xB8, 0x03,
-                    ], // "Midi Service"
-                ),
-                Attribute::new(
-                    Uuid16(0x2803).into(), // "Characteristic"
-                    Handle::from_raw(0x0002),
-                    &[
-                        0x02 | 0x08 | 0x04 | 0x10, // 1 byte properties: READ = 0x02, WRITE_REQ = 0x08, WRITE_CMD = 0x04, NOTIFICATION = 0x10
-                        0x03,
-                        0x00, // 2 bytes handle = 0x0003
-                        // the actual UUID
-                        0xF3,
-                        0x6B,
-                        0x10,
-                        0x9D,
-                        0x66,
-                        0xF2, /*-*/
-                        0xA9,
-                        0xA1, /*-*/
-                        0x12,
-                        0x41, /*-*/
-                        0x68,
-                        0x38, /*-*/
-                        0xDB,
-                        0xE5,
-                        0x72,
-                        0x77,
-                    ],
-                ),
-                // Characteristic value (Empty Packet)
-                Attribute::new(
-                    AttUuid::Uuid128(Uuid128::from_bytes([
-                        0xF3, 0x6B, 0x10, 0x9D, 0x66, 0xF2, /*-*/
-                        0xA9, 0xA1, /*-*/
-                        0x12, 0x41, /*-*/
-                        0x68, 0x38, /*-*/
-                        0xDB, 0xE5, 0x72, 0x77,
-                    ])),
-                    Handle::from_raw(0x0003),
-                    &[],
-                ),
-                // CCCD
-                Attribute::new(
-                    AttUuid::Uuid16(Uuid16(0x2902)),
-                    Handle::from_raw(0x0004),
-                    &[0x00, 0x00],
-                ),
-            ],
-        }
+        const SERVICE_UUID: [u8; 16] = [
+            0x00, 0xC7, 0xC4, 0x4E, 0xE3, 0x6C, /* - */
+            0x51, 0xA7, /* - */
+            0x33, 0x4B, /* - */
+            0xE8, 0xED, /* - */
+            0x5A, 0x0E, 0xB8, 0x03,
+        ];
+        const CHARACTERISTIC_UUID: [u8; 16] = [
+            0xF3, 0x6B, 0x10, 0x9D, 0x66, 0xF2, /*-*/
+            0xA9, 0xA1, /*-*/
+            0x12, 0x41, /*-*/
+            0x68, 0x38, /*-*/
+            0xDB, 0xE5, 0x72, 0x77,
+        ];
+
+        let service = ServiceBuilder::<4>::new(
+            AttUuid::Uuid128(Uuid128::from_bytes(SERVICE_UUID)),
+            &SERVICE_UUID,
+        )
+        .characteristic(
+            CharacteristicSpec::new(
+                AttUuid::Uuid128(Uuid128::from_bytes(CHARACTERISTIC_UUID)),
+                Properties::READ
+                    | Properties::WRITE_WITHOUT_RESPONSE
+                    | Properties::WRITE
+                    | Properties::NOTIFY,
+                &[], // Empty Packet
+            )
+            .with_cccd(),
+        )
+        .build();
+
+        Self { service }
     }
 }
 
@@ -158,34 +153,254 @@ impl AttributeProvider for MidiServiceAttrs {
     fn for_attrs_in_range(
         &mut self,
         range: HandleRange,
-        mut f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
+        f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
     ) -> Result<(), Error> {
-        let count = self.attributes.len();
-        let start = usize::from(range.start().as_u16() - 1); // handles start at 1, not 0
-        let end = usize::from(range.end().as_u16() - 1);
+        let this: &Self = self;
+        characteristic::for_attrs_in_range(this, this.service.attributes(), range, f)
+    }
+
+    fn is_grouping_attr(&self, uuid: AttUuid) -> bool {
+        characteristic::is_grouping_attr(uuid)
+    }
+
+    fn group_end(&self, handle: Handle) -> Option<&Attribute<dyn AsRef<[u8]>>> {
+        characteristic::group_end(self.service.attributes(), handle)
+    }
+}
+
+impl NotifySource for MidiServiceAttrs {
+    fn cccd(&self) -> CccdState {
+        self.service
+            .cccd_state(Self::value_handle())
+            .unwrap_or_default()
+    }
+
+    fn on_cccd_write(&mut self, value: u16) {
+        self.service
+            .on_write(Self::cccd_handle(), &value.to_le_bytes());
+    }
+
+    fn value_handle(&self) -> Handle {
+        Self::value_handle()
+    }
+
+    fn indication_pending(&self) -> bool {
+        self.service.indication_pending(Self::value_handle())
+    }
+
+    fn set_indication_pending(&mut self, pending: bool) {
+        self.service
+            .set_indication_pending(Self::value_handle(), pending);
+    }
+}
+
+/// ATT opcode for a Handle Value Notification.
+const OP_HANDLE_VALUE_NOTIFICATION: u8 = 0x1B;
+/// ATT opcode for a Handle Value Indication.
+const OP_HANDLE_VALUE_INDICATION: u8 = 0x1D;
 
-        let attrs = if start >= count {
-            &[]
+/// The enable bits of a Client Characteristic Configuration Descriptor (CCCD, UUID 0x2902).
+///
+/// A client writes this 2-byte value to subscribe to (or unsubscribe from) notifications and/or
+/// indications for a characteristic.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CccdState {
+    bits: u16,
+}
+
+impl CccdState {
+    const NOTIFICATIONS: u16 = 0x0001;
+    const INDICATIONS: u16 = 0x0002;
+
+    /// Creates a `CccdState` from the raw bits written by a client.
+    pub fn from_raw(bits: u16) -> Self {
+        Self { bits }
+    }
+
+    /// Returns whether the client has enabled notifications.
+    pub fn notifications_enabled(&self) -> bool {
+        self.bits & Self::NOTIFICATIONS != 0
+    }
+
+    /// Returns whether the client has enabled indications.
+    pub fn indications_enabled(&self) -> bool {
+        self.bits & Self::INDICATIONS != 0
+    }
+}
+
+/// A Handle Value Notification or Indication, queued for transmission by an [`AttributeProvider`]
+/// that implements [`NotifySource`].
+///
+/// Indications additionally require the peer to reply with a Handle Value Confirmation before
+/// another indication may be sent on the same connection; [`Notification::requires_confirmation`]
+/// tells the caller whether it needs to wait for one.
+pub struct Notification<'a> {
+    handle: Handle,
+    value: &'a [u8],
+    requires_confirmation: bool,
+}
+
+impl<'a> Notification<'a> {
+    /// Creates a `Notification`; used by [`NotifySource::notify`].
+    pub(crate) fn new(handle: Handle, value: &'a [u8], requires_confirmation: bool) -> Self {
+        Self {
+            handle,
+            value,
+            requires_confirmation,
+        }
+    }
+
+    /// The characteristic value handle this notification/indication is for.
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// The value being notified/indicated.
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Whether the peer must send back a Handle Value Confirmation before another indication can
+    /// be sent.
+    pub fn requires_confirmation(&self) -> bool {
+        self.requires_confirmation
+    }
+
+    /// Encodes this notification/indication as its ATT PDU (opcode, handle, value) into `buf`.
+    ///
+    /// Returns the number of bytes written. `buf` must be at least `3 + self.value().len()` bytes
+    /// long.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        let opcode = if self.requires_confirmation {
+            OP_HANDLE_VALUE_INDICATION
         } else {
-            let end = cmp::min(count - 1, end);
-            &self.attributes[start..=end]
+            OP_HANDLE_VALUE_NOTIFICATION
         };
 
-        for attr in attrs {
-            f(self, attr)?;
+        let len = 3 + self.value.len();
+        buf[0] = opcode;
+        buf[1..3].copy_from_slice(&self.handle.as_u16().to_le_bytes());
+        buf[3..len].copy_from_slice(self.value);
+        len
+    }
+}
+
+/// Extends an [`AttributeProvider`] that owns a CCCD with the ability to queue notifications and
+/// indications for the characteristic value the CCCD configures.
+///
+/// Implementors only need to say where their CCCD enable bits live, which handle the
+/// characteristic value has, and where to track an in-flight indication; [`NotifySource::notify`]
+/// then does the rest, including enforcing that at most one indication is outstanding at a time
+/// (Core Spec Vol 3, Part G, §3.3.1.1).
+pub trait NotifySource: AttributeProvider {
+    /// Returns the current CCCD enable bits.
+    fn cccd(&self) -> CccdState;
+
+    /// Updates the CCCD enable bits after a peer writes to the CCCD attribute.
+    ///
+    /// The GATT server should call this whenever a write lands on the CCCD's handle.
+    fn on_cccd_write(&mut self, value: u16);
+
+    /// The characteristic value handle notifications/indications are sent for.
+    fn value_handle(&self) -> Handle;
+
+    /// Returns whether an indication has been sent and not yet confirmed by the peer.
+    fn indication_pending(&self) -> bool;
+
+    /// Records whether an indication has been sent and not yet confirmed by the peer.
+    fn set_indication_pending(&mut self, pending: bool);
+
+    /// Queues a Handle Value Notification or Indication for `value`, preferring an indication if
+    /// the peer enabled both.
+    ///
+    /// Returns `None` if the peer has enabled neither notifications nor indications, or if an
+    /// indication is requested but one is already outstanding — the caller must wait for
+    /// [`NotifySource::confirm`] before the next one can be sent.
+    fn notify<'a>(&mut self, value: &'a [u8]) -> Option<Notification<'a>> {
+        let cccd = self.cccd();
+        if cccd.indications_enabled() {
+            if self.indication_pending() {
+                return None;
+            }
+            self.set_indication_pending(true);
+            Some(Notification::new(self.value_handle(), value, true))
+        } else if cccd.notifications_enabled() {
+            Some(Notification::new(self.value_handle(), value, false))
+        } else {
+            None
         }
-        Ok(())
     }
 
-    fn is_grouping_attr(&self, uuid: AttUuid) -> bool {
-        uuid == Uuid16(0x2800) // FIXME not characteristics?
+    /// Clears the in-flight indication flag after the peer sends a Handle Value Confirmation.
+    ///
+    /// The GATT server should call this whenever it receives a confirmation for this
+    /// characteristic.
+    fn confirm(&mut self) {
+        self.set_indication_pending(false);
     }
+}
 
-    fn group_end(&self, handle: Handle) -> Option<&Attribute<dyn AsRef<[u8]>>> {
-        match handle.as_u16() {
-            0x0001 => Some(&self.attributes[3]),
-            0x0002 => Some(&self.attributes[3]),
-            _ => None,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cccd_state_decodes_enable_bits() {
+        let none = CccdState::from_raw(0x0000);
+        assert!(!none.notifications_enabled());
+        assert!(!none.indications_enabled());
+
+        let both = CccdState::from_raw(0x0003);
+        assert!(both.notifications_enabled());
+        assert!(both.indications_enabled());
+    }
+
+    #[test]
+    fn notification_encodes_opcode_handle_and_value() {
+        let notification = Notification::new(Handle::from_raw(0x0003), &[1, 2, 3], false);
+        let mut buf = [0u8; 8];
+        let len = notification.encode(&mut buf);
+        assert_eq!(
+            &buf[..len],
+            &[OP_HANDLE_VALUE_NOTIFICATION, 0x03, 0x00, 1, 2, 3]
+        );
+
+        let indication = Notification::new(Handle::from_raw(0x0003), &[9], true);
+        let len = indication.encode(&mut buf);
+        assert_eq!(&buf[..len], &[OP_HANDLE_VALUE_INDICATION, 0x03, 0x00, 9]);
+    }
+
+    #[test]
+    fn notify_prefers_indication_over_notification() {
+        let mut attrs = BatteryServiceAttrs::default();
+        attrs.on_cccd_write(0x0003); // both notifications and indications enabled
+
+        let notification = attrs.notify(&[1]).expect("peer is subscribed");
+        assert!(notification.requires_confirmation());
+    }
+
+    #[test]
+    fn notify_withholds_second_indication_until_confirmed() {
+        let mut attrs = BatteryServiceAttrs::default();
+        attrs.on_cccd_write(0x0002); // indications only
+
+        assert!(attrs.notify(&[1]).is_some());
+        assert!(
+            attrs.notify(&[2]).is_none(),
+            "a second indication must wait for the first to be confirmed"
+        );
+
+        attrs.confirm();
+        assert!(attrs.notify(&[3]).is_some());
+    }
+
+    #[test]
+    fn notify_does_not_gate_plain_notifications() {
+        let mut attrs = BatteryServiceAttrs::default();
+        attrs.on_cccd_write(0x0001); // notifications only
+
+        let first = attrs.notify(&[1]).expect("peer is subscribed");
+        assert!(!first.requires_confirmation());
+        assert!(attrs.notify(&[2]).is_some());
     }
 }
@@ -140,14 +140,466 @@ impl DataChannel {
     }
 }
 
+/// The over-the-air PHY (modulation and, where applicable, FEC coding) to transmit or receive
+/// with.
+///
+/// All channel types in this module assume the classic `Le1M` PHY; the BLE 5 PHYs below trade
+/// throughput for range, or vice versa, without changing channel indices or center frequencies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+pub enum PhyMode {
+    /// Uncoded 1 Mbit/s GFSK. The original BLE PHY, supported by every BLE radio.
+    Le1M,
+    /// Uncoded 2 Mbit/s GFSK (BLE 5), doubling the raw throughput of `Le1M`.
+    Le2M,
+    /// LE Coded PHY (BLE 5) with S=2 forward error correction, for extended range.
+    LeCodedS2,
+    /// LE Coded PHY (BLE 5) with S=8 forward error correction, for maximum range.
+    LeCodedS8,
+}
+
+impl PhyMode {
+    /// Returns the length of the preamble this PHY requires before the access address, in
+    /// microseconds.
+    pub fn preamble_us(&self) -> u8 {
+        match self {
+            PhyMode::Le1M => 8,
+            // The 2 Mbit/s PHY still sends a 16-*bit* preamble, but at twice the symbol rate, so
+            // it takes the same 8 us as Le1M's 8-bit preamble at 1 Mbit/s.
+            PhyMode::Le2M => 8,
+            PhyMode::LeCodedS2 | PhyMode::LeCodedS8 => 80,
+        }
+    }
+}
+
+impl Default for PhyMode {
+    /// Returns `Le1M`, the PHY every BLE radio must support.
+    fn default() -> Self {
+        PhyMode::Le1M
+    }
+}
+
+/// Configuration for a single [`Radio::transmit`] or [`Radio::receive`] call: which channel to
+/// use it on and which PHY to use.
+#[derive(Copy, Clone, Debug, defmt::Format)]
+pub struct RadioConfig {
+    /// Center frequency to transmit/receive on, in MHz (see e.g. [`DataChannel::freq`]).
+    pub freq: u16,
+    /// The PHY (and implied preamble length) to transmit/receive with.
+    pub phy: PhyMode,
+}
+
+impl RadioConfig {
+    /// Creates a `RadioConfig` for `freq` MHz, using the default `Le1M` PHY.
+    pub fn new(freq: u16) -> Self {
+        Self {
+            freq,
+            phy: PhyMode::default(),
+        }
+    }
+
+    /// Sets the PHY to use, returning the updated config.
+    pub fn with_phy(mut self, phy: PhyMode) -> Self {
+        self.phy = phy;
+        self
+    }
+}
+
+/// The outcome of a [`Radio`] transmit or receive operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+pub enum RadioResult {
+    /// The operation completed successfully.
+    Ok,
+    /// The operation was aborted (e.g. cancelled or timed out) before it could complete.
+    Aborted,
+}
+
+/// Notified once a [`Radio::transmit`] completes and hands the transmit buffer back.
+pub trait TxClient {
+    /// Called once `buf` has been fully transmitted (or the transmit was aborted).
+    ///
+    /// `buf` is the same buffer that was passed to [`Radio::transmit`]; ownership is returned here
+    /// so it can be reused, e.g. for the next transmission.
+    fn send_done(&self, buf: &'static mut [u8], result: RadioResult);
+}
+
+/// Notified once a [`Radio::receive`] completes and hands the receive buffer back.
+pub trait RxClient {
+    /// Called once a packet has been received into `buf` (or the receive was aborted).
+    ///
+    /// `len` is the number of valid bytes written to the front of `buf`. `crc_ok` reports whether
+    /// the received CRC matched (see the [`crc`][crate::crc] module); `result` reports whether the
+    /// radio operation itself completed without being aborted.
+    fn receive(&self, buf: &'static mut [u8], len: u8, crc_ok: bool, result: RadioResult);
+}
+
 /// Trait for raw 2.4 GHz non-BLE-specific radios.
 ///
 /// You probably won't need to implement this trait, unless you're working with hardware that has
 /// absolutely no special support for BLE. Usually, the Link-Layer `Transmitter` should be
 /// implemented.
+///
+/// This is modeled as a non-blocking, completion-callback API (as is common for embedded radio
+/// HILs): `transmit`/`receive` hand a `'static` buffer to the hardware and return immediately, and
+/// ownership of the buffer comes back later via the registered [`TxClient`]/[`RxClient`]. This
+/// lets the same static buffer be recycled between TX and RX without ever copying it.
 pub trait Radio {
-    /// Transmit every Byte in `buf` over the air, LSb first, at `freq` MHz.
+    /// Registers the client to notify when a `transmit` completes.
+    fn set_transmit_client(&mut self, client: &'static dyn TxClient);
+
+    /// Registers the client to notify when a `receive` completes.
+    fn set_receive_client(&mut self, client: &'static dyn RxClient);
+
+    /// Starts transmitting every byte in `buf`, LSb first, per `config`.
+    ///
+    /// Returns immediately. `buf` is handed back through the registered [`TxClient`] once the
+    /// packet is on air.
     ///
     /// TODO: Document all radio requirements
-    fn transmit(&mut self, buf: &mut [u8], freq: u16);
+    fn transmit(&mut self, buf: &'static mut [u8], config: RadioConfig);
+
+    /// Starts listening for a single incoming packet per `config`, writing it into `buf`.
+    ///
+    /// Returns immediately. `buf` is handed back through the registered [`RxClient`] once a packet
+    /// has been received (or the receive is aborted).
+    fn receive(&mut self, buf: &'static mut [u8], config: RadioConfig);
+
+    /// Returns the inclusive range of TX power levels, in dBm, this radio supports.
+    fn supported_tx_power_dbm(&self) -> core::ops::RangeInclusive<i8>;
+
+    /// Sets the radio's TX power to the supported level closest to `dbm`.
+    ///
+    /// Implementors usually only support a handful of discrete levels (mirroring the TX-power
+    /// table exposed by the underlying hardware) and should round to the nearest one they support.
+    fn set_tx_power_dbm_unchecked(&mut self, dbm: i8);
+
+    /// Sets the transmit power, in dBm, clamping it to
+    /// [`supported_tx_power_dbm`][Self::supported_tx_power_dbm] before handing it to the radio.
+    fn set_tx_power(&mut self, dbm: i8) {
+        let range = self.supported_tx_power_dbm();
+        let clamped = dbm.clamp(*range.start(), *range.end());
+        self.set_tx_power_dbm_unchecked(clamped);
+    }
+}
+
+/// The completion state a [`BlockingRadio`] is notified through.
+///
+/// This is a separate type (rather than living directly on `BlockingRadio`) because the
+/// [`TxClient`]/[`RxClient`] registered with a [`Radio`] must be `'static`, while `BlockingRadio`
+/// itself is typically borrowed for just the duration of one blocking call. Callers provide a
+/// `&'static BlockingRadioState` (e.g. backed by a `static`) once, up front; `BlockingRadio` then
+/// only ever needs a plain borrow of it.
+#[derive(Default)]
+pub struct BlockingRadioState {
+    tx_done: core::cell::Cell<Option<(&'static mut [u8], RadioResult)>>,
+    rx_done: core::cell::Cell<Option<(&'static mut [u8], u8, bool, RadioResult)>>,
+}
+
+impl BlockingRadioState {
+    /// Creates an empty `BlockingRadioState`.
+    pub const fn new() -> Self {
+        Self {
+            tx_done: core::cell::Cell::new(None),
+            rx_done: core::cell::Cell::new(None),
+        }
+    }
+}
+
+impl TxClient for BlockingRadioState {
+    fn send_done(&self, buf: &'static mut [u8], result: RadioResult) {
+        self.tx_done.set(Some((buf, result)));
+    }
+}
+
+impl RxClient for BlockingRadioState {
+    fn receive(&self, buf: &'static mut [u8], len: u8, crc_ok: bool, result: RadioResult) {
+        self.rx_done.set(Some((buf, len, crc_ok, result)));
+    }
+}
+
+/// A blocking adapter over an async [`Radio`], for simple radios and call sites that don't need
+/// overlapped transmit/receive.
+///
+/// Registers a [`BlockingRadioState`] as both the [`TxClient`] and [`RxClient`] once, at
+/// construction, and polls it from `blocking_transmit`/`blocking_receive`.
+pub struct BlockingRadio<R> {
+    radio: R,
+    state: &'static BlockingRadioState,
+}
+
+impl<R: Radio> BlockingRadio<R> {
+    /// Wraps `radio` in a blocking adapter, registering `state` as its TX/RX client.
+    pub fn new(mut radio: R, state: &'static BlockingRadioState) -> Self {
+        radio.set_transmit_client(state);
+        radio.set_receive_client(state);
+        Self { radio, state }
+    }
+
+    /// Transmits `buf` and blocks until the radio has finished sending it.
+    pub fn blocking_transmit(
+        &mut self,
+        buf: &'static mut [u8],
+        config: RadioConfig,
+    ) -> (&'static mut [u8], RadioResult) {
+        self.radio.transmit(buf, config);
+        loop {
+            if let Some(done) = self.state.tx_done.take() {
+                return done;
+            }
+        }
+    }
+
+    /// Listens for a single packet into `buf` and blocks until the radio has finished receiving
+    /// it.
+    pub fn blocking_receive(
+        &mut self,
+        buf: &'static mut [u8],
+        config: RadioConfig,
+    ) -> (&'static mut [u8], u8, bool, RadioResult) {
+        self.radio.receive(buf, config);
+        loop {
+            if let Some(done) = self.state.rx_done.take() {
+                return done;
+            }
+        }
+    }
+}
+
+/// Applies BLE data whitening (and de-whitening) to PDU and CRC bytes.
+///
+/// Hardware BLE radios usually whiten and de-whiten data on their own, but a [`Radio`] has no
+/// special BLE support and needs this to be done in software before `transmit` and after
+/// receiving.
+///
+/// Whitening runs a 7-bit LFSR (polynomial `x^7 + x^4 + 1`) seeded from the channel's
+/// [`whitening_iv`][AdvertisingChannel::whitening_iv], XORing one LFSR output bit into every data
+/// bit, LSb first. XOR is its own inverse, so running the exact same process again de-whitens the
+/// data, which is why a single type and method cover both directions.
+#[derive(Copy, Clone, Debug)]
+pub struct Whitener {
+    /// The 7 LFSR bits, stored the same way as `whitening_iv`: Position 0 is bit 6, Position 6 is
+    /// bit 0 (the LSb).
+    lfsr: u8,
+}
+
+impl Whitener {
+    /// Creates a `Whitener` seeded for whitening/de-whitening on `channel`.
+    pub fn new(channel: AdvertisingChannel) -> Self {
+        Self {
+            lfsr: channel.whitening_iv(),
+        }
+    }
+
+    /// Creates a `Whitener` seeded for whitening/de-whitening on `channel`.
+    pub fn new_data_channel(channel: DataChannel) -> Self {
+        Self {
+            lfsr: channel.whitening_iv(),
+        }
+    }
+
+    /// Whitens (or de-whitens) `data` in place.
+    ///
+    /// This must be called on the whole PDU plus its trailing 3-byte CRC.
+    pub fn whiten(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte = self.whiten_byte(*byte);
+        }
+    }
+
+    /// Whitens a single byte, processing its bits LSb first, as required by the over-the-air bit
+    /// order.
+    fn whiten_byte(&mut self, byte: u8) -> u8 {
+        let mut out = 0;
+        for i in 0..8 {
+            let bit = (byte >> i) & 1;
+            out |= (bit ^ self.next_bit()) << i;
+        }
+        out
+    }
+
+    /// Returns the whitening bit for the next data bit (the Position-6 term) and clocks the LFSR.
+    fn next_bit(&mut self) -> u8 {
+        // Position 6 sits at the LSb, so it's both the output bit and the bit that is about to be
+        // shifted out of the register.
+        let feedback = self.lfsr & 1;
+        // Shift the register toward Position 6.
+        self.lfsr = (self.lfsr >> 1) | (feedback << 6);
+        // A `1` bit shifted out is fed back into Position 0 and XORed into Position 4 (the `x^4`
+        // term of the polynomial, fed from Position 3).
+        if feedback == 1 {
+            self.lfsr ^= 0b0000_0100;
+        }
+        feedback
+    }
+}
+
+/// A 37-bit map of which data channels (indices 0..=36) are used by a connection.
+///
+/// Channels can be marked unused to avoid ones with persistent interference. The Link-Layer must
+/// ensure at least two channels stay used, see [`ChannelMap::has_min_used_channels`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+pub struct ChannelMap {
+    /// Bit `i` is set if data channel `i` is used. Only bits 0..=36 are meaningful.
+    map: u64,
+}
+
+impl ChannelMap {
+    /// Creates a `ChannelMap` from a raw 37-bit used/unused bitfield (bit `i` = channel `i`).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `map` has any bit above bit 36 set.
+    pub fn from_raw(map: u64) -> Self {
+        assert!(map < (1 << 37), "channel map must only use bits 0..=36");
+        Self { map }
+    }
+
+    /// Returns whether data channel `channel_index` (0..=36) is marked as used.
+    pub fn is_used(&self, channel_index: u8) -> bool {
+        self.map & (1 << channel_index) != 0
+    }
+
+    /// Returns the number of channels marked as used.
+    pub fn used_count(&self) -> u32 {
+        self.map.count_ones()
+    }
+
+    /// Returns whether this map satisfies the Link-Layer invariant of at least two used channels.
+    pub fn has_min_used_channels(&self) -> bool {
+        self.used_count() >= 2
+    }
+
+    /// Returns the data channel that is the `n`th used channel, scanning from channel 0 upwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n >= self.used_count()`.
+    fn nth_used(&self, n: u32) -> DataChannel {
+        let mut remaining = n;
+        for index in 0..=36 {
+            if self.is_used(index) {
+                if remaining == 0 {
+                    return DataChannel::new(index);
+                }
+                remaining -= 1;
+            }
+        }
+        panic!("`n` out of range of used channels");
+    }
+}
+
+/// Channel Selection Algorithm #1 (CSA#1), stepping through the `DataChannel` to use on
+/// successive connection events.
+///
+/// Call [`Iterator::next`] once per connection event to obtain the channel for that event.
+#[derive(Copy, Clone, Debug)]
+pub struct ChannelSelection {
+    hop_increment: u8,
+    map: ChannelMap,
+    last_unmapped_channel: u8,
+}
+
+impl ChannelSelection {
+    /// Creates a `ChannelSelection` for a connection's `hopIncrement` and channel map.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `hop_increment` is not in range 5..=16, or if `map` does not have at
+    /// least 2 used channels (see [`ChannelMap::has_min_used_channels`]).
+    pub fn new(hop_increment: u8, map: ChannelMap) -> Self {
+        assert!(
+            (5..=16).contains(&hop_increment),
+            "hopIncrement must be in range 5..=16"
+        );
+        assert!(
+            map.has_min_used_channels(),
+            "channel map must have at least 2 used channels"
+        );
+
+        Self {
+            hop_increment,
+            map,
+            last_unmapped_channel: 0,
+        }
+    }
+}
+
+impl Iterator for ChannelSelection {
+    type Item = DataChannel;
+
+    /// Computes the `DataChannel` to use for the next connection event.
+    fn next(&mut self) -> Option<DataChannel> {
+        let unmapped_channel =
+            (u16::from(self.last_unmapped_channel) + u16::from(self.hop_increment)) % 37;
+        let unmapped_channel = unmapped_channel as u8;
+        self.last_unmapped_channel = unmapped_channel;
+
+        Some(if self.map.is_used(unmapped_channel) {
+            DataChannel::new(unmapped_channel)
+        } else {
+            let remapping_index = unmapped_channel % self.map.used_count() as u8;
+            self.map.nth_used(u32::from(remapping_index))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors locking down the bit order: the LFSR is seeded via `whitening_iv` and run
+    /// over a few bytes, comparing against precomputed whitened output.
+    #[test]
+    fn whitens_channel_0() {
+        let mut whitener = Whitener::new_data_channel(DataChannel::new(0));
+        let mut data = [0x00, 0x00, 0xFF, 0xAA];
+        whitener.whiten(&mut data);
+        assert_eq!(data, [0x40, 0xB2, 0x43, 0x69]);
+    }
+
+    #[test]
+    fn whitens_channel_37() {
+        let mut whitener = Whitener::new(AdvertisingChannel::first());
+        let mut data = [0x00, 0x00, 0xFF, 0xAA];
+        whitener.whiten(&mut data);
+        assert_eq!(data, [0x8D, 0xD2, 0xA8, 0x0B]);
+    }
+
+    #[test]
+    fn whitening_is_its_own_inverse() {
+        let original = [0x12, 0x34, 0x56, 0x78, 0x9A];
+
+        let mut data = original;
+        Whitener::new(AdvertisingChannel::first()).whiten(&mut data);
+        assert_ne!(data, original);
+        Whitener::new(AdvertisingChannel::first()).whiten(&mut data);
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    #[should_panic]
+    fn channel_map_rejects_too_few_used_channels() {
+        let map = ChannelMap::from_raw(1 << 5); // only 1 channel used
+        ChannelSelection::new(7, map);
+    }
+
+    #[test]
+    fn channel_selection_remaps_on_sparse_map() {
+        // Only channels 0, 1 and 36 are used, so most hops land on an unused channel and must be
+        // remapped.
+        let map = ChannelMap::from_raw((1 << 0) | (1 << 1) | (1 << 36));
+        let selection = ChannelSelection::new(7, map);
+
+        let channels: Vec<u8> = selection.take(6).map(|ch| ch.index()).collect();
+        assert_eq!(channels, [1, 36, 0, 1, 36, 36]);
+    }
+
+    #[test]
+    fn preamble_us_matches_core_spec() {
+        // Le1M and Le2M both take 8 us: Le1M sends an 8-bit preamble at 1 Mbit/s, Le2M a 16-bit
+        // preamble at 2 Mbit/s. The coded PHYs use a fixed 80 us preamble regardless of S value.
+        assert_eq!(PhyMode::Le1M.preamble_us(), 8);
+        assert_eq!(PhyMode::Le2M.preamble_us(), 8);
+        assert_eq!(PhyMode::LeCodedS2.preamble_us(), 80);
+        assert_eq!(PhyMode::LeCodedS8.preamble_us(), 80);
+    }
 }